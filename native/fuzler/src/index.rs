@@ -0,0 +1,143 @@
+//! Token-blocked corpus index, used to avoid all-pairs scoring on large
+//! corpora (dedup / record-linkage workloads).
+//!
+//! The corpus is held as owned `String`s in the resource itself, and an
+//! inverted index maps each whitespace token to the document ids containing
+//! it. Postings are keyed by an owned `String` per distinct token: one alloc
+//! per distinct token per document at build time, negligible next to the
+//! `similarity_score` cost this feature exists to avoid, and it sidesteps
+//! the self-referential-struct problems a borrowed-`&str` key would need
+//! unsafe code to work around. A query gathers candidates as the union of
+//! postings lists, discards any sharing fewer than `min_shared_tokens`
+//! distinct tokens ("blocking"), and scores only the survivors with the
+//! existing `similarity_score` pipeline.
+
+use rustc_hash::{FxHashMap, FxHashSet};
+use rustler::Resource;
+
+pub struct CorpusIndex {
+    docs: Vec<String>,
+    postings: FxHashMap<String, Vec<u32>>,
+}
+
+impl CorpusIndex {
+    pub fn empty() -> Self {
+        Self {
+            docs: Vec::new(),
+            postings: FxHashMap::default(),
+        }
+    }
+
+    pub fn build(corpus: Vec<String>) -> Self {
+        let mut postings: FxHashMap<String, Vec<u32>> = FxHashMap::default();
+        for (doc_id, doc) in corpus.iter().enumerate() {
+            // Dedupe within a doc so a repeated token doesn't inflate its
+            // share of the blocking count below.
+            let doc_tokens: FxHashSet<&str> = doc.split_whitespace().collect();
+            for tok in doc_tokens {
+                postings
+                    .entry(tok.to_string())
+                    .or_default()
+                    .push(doc_id as u32);
+            }
+        }
+
+        Self {
+            docs: corpus,
+            postings,
+        }
+    }
+
+    /// Score every document sharing at least `min_shared_tokens` distinct
+    /// tokens with `query`, returning `(doc_id, score)` pairs sorted by
+    /// descending score. Only documents sharing at least one token are
+    /// candidates, so a `min_shared_tokens` of 0 behaves like 1.
+    pub fn query(&self, query: &str, min_shared_tokens: usize) -> Vec<(u32, f64)> {
+        let mut shared_counts: FxHashMap<u32, u32> = FxHashMap::default();
+        let mut seen_tokens: FxHashSet<&str> = FxHashSet::default();
+
+        for tok in query.split_whitespace() {
+            if !seen_tokens.insert(tok) {
+                continue;
+            }
+            if let Some(doc_ids) = self.postings.get(tok) {
+                for &doc_id in doc_ids {
+                    *shared_counts.entry(doc_id).or_insert(0) += 1;
+                }
+            }
+        }
+
+        let mut scored: Vec<(u32, f64)> = shared_counts
+            .into_iter()
+            .filter(|&(_, shared)| shared as usize >= min_shared_tokens)
+            .map(|(doc_id, _)| {
+                let doc = self.docs[doc_id as usize].as_str();
+                (doc_id, crate::similarity_score(query, doc))
+            })
+            .collect();
+
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scored
+    }
+}
+
+/// BEAM-visible handle wrapping a built `CorpusIndex`.
+pub struct IndexResource(pub CorpusIndex);
+
+#[rustler::resource_impl]
+impl Resource for IndexResource {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_finds_the_exact_match_first() {
+        let index = CorpusIndex::build(vec![
+            "the quick brown fox".to_string(),
+            "a slow green turtle".to_string(),
+            "the quick brown dog".to_string(),
+        ]);
+
+        let results = index.query("the quick brown fox", 1);
+
+        assert_eq!(results[0].0, 0);
+        assert_eq!(results[0].1, 1.0);
+    }
+
+    #[test]
+    fn query_discards_documents_below_min_shared_tokens() {
+        let index = CorpusIndex::build(vec![
+            "alpha beta gamma".to_string(),
+            "alpha only".to_string(),
+            "nothing in common".to_string(),
+        ]);
+
+        // "nothing in common" shares zero tokens with the query, so it's
+        // never even a candidate; "alpha only" shares one ("alpha"), which
+        // clears a min of 1 but not a min of 2.
+        let loose = index.query("alpha beta", 1);
+        assert!(loose.iter().any(|&(id, _)| id == 1));
+
+        let strict = index.query("alpha beta", 2);
+        assert!(!strict.iter().any(|&(id, _)| id == 1));
+        assert!(strict.iter().any(|&(id, _)| id == 0));
+    }
+
+    #[test]
+    fn query_against_empty_index_returns_nothing() {
+        let index = CorpusIndex::empty();
+        assert!(index.query("anything", 0).is_empty());
+    }
+
+    #[test]
+    fn build_dedupes_repeated_tokens_within_a_document_for_blocking() {
+        // "repeat repeat repeat" should count as sharing exactly one distinct
+        // token with the query, not three, or `min_shared_tokens` blocking
+        // would be trivially easy to clear against repetitive documents.
+        let index = CorpusIndex::build(vec!["repeat repeat repeat".to_string()]);
+
+        assert!(index.query("repeat", 1).iter().any(|&(id, _)| id == 0));
+        assert!(index.query("repeat", 2).is_empty());
+    }
+}
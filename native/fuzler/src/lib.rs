@@ -7,6 +7,34 @@
 //! * Zero‑copy sliding windows: we slice the original `&str` instead of allocating.
 //! * `FxHashMap<&str, u32>` avoids heap strings during token‑bag counts.
 //! * Character metric: Hamming fast‑path, then SIMD Levenshtein (`triple_accel`).
+//! * Batch ranking (`nif_similarity_top_k`) tokenises the query once and fans the
+//!   scoring of every candidate out across a `rayon` thread pool, folding each
+//!   worker's partial results into a bounded min‑heap so memory stays flat
+//!   regardless of corpus size.
+//! * Byte‑frequency lower bound: when `char_similarity` is given a `threshold`
+//!   it can't possibly clear, it returns the similarity implied by an
+//!   edit‑distance lower bound straight away, skipping the banded DP; the sliding‑window loop in
+//!   `partial_similarity` feeds it the running `best` so most windows are
+//!   rejected in O(n) instead of O(n·k). That running `best` is itself
+//!   floored at `ScoringConfig::cutoff`, so a caller with its own acceptance
+//!   bar (via `nif_similarity_score_with`) gets the prefilter applied against
+//!   *its* bar from the very first window, not just once an in‑progress
+//!   score happens to clear it.
+//! * Corpus index (`index` module): an inverted token → doc‑id postings
+//!   list held behind a NIF `Resource`, so dedup/record‑linkage workloads
+//!   can block candidates down to a shared‑token minimum before paying for
+//!   `similarity_score`, instead of scoring every pair.
+//! * Similarity cache (`cache` module): an LRU keyed by a canonically
+//!   ordered pair of BLAKE3 digests, so repeated comparisons of the same
+//!   large strings in incremental pipelines skip recomputation entirely.
+//! * Scoring profile (`config` module): blend weights, window/chunk sizing,
+//!   the partial‑similarity token cutoff, and a configurable acceptance
+//!   `cutoff` for the byte‑frequency prefilter live in a `ScoringConfig`
+//!   instead of hard‑coded constants, so `nif_similarity_score_with` lets
+//!   callers tune precision/recall (and bail‑out cost) per domain;
+//!   `nif_similarity_score` keeps running on `ScoringConfig::default()`,
+//!   whose `cutoff: 0.0` adds no bar beyond the sliding‑window loop's running
+//!   `best`, so its scores are unchanged.
 //!
 //! Safety & Reliability
 //! --------------------
@@ -18,22 +46,26 @@
 //!   • 20‑token vs 200‑token → ≈ 110 µs  │ 1‑token vs 1‑token → ≈ 3 µs
 //!   • 50 ASCII chars vs 50 → ≈ 8 µs
 
+use rayon::prelude::*;
 use rustc_hash::FxHashMap;
-use rustler::{Encoder, Env, NifResult, Term};
+use rustler::{Encoder, Env, NifResult, ResourceArc, Term};
 use smallvec::SmallVec;
-use std::cmp::{max, min};
+use std::cmp::{max, min, Ordering};
+use std::collections::BinaryHeap;
 use std::panic;
 use tracing::error;
 
 use triple_accel::hamming::hamming;
 use triple_accel::levenshtein::levenshtein_simd_k;
 
+mod cache;
+mod config;
+mod index;
+use config::ScoringConfig;
+use index::{CorpusIndex, IndexResource};
+
 const HAMMING_WINDOW: usize = 2; // ±2‑byte window for Hamming fast‑path
-const SHORT_STRING: usize = 64; // band size for Levenshtein
 const ROUND_TO: f64 = 100.0; // two‑decimal rounding
-const CHUNK_MIN: usize = 50; // min tokens per chunk in aggregated mode
-const CHUNK_MAX: usize = 100; // max tokens per chunk in aggregated mode
-const WINDOW_PAD: f32 = 0.30; // ±30 % padding around query length in sliding window
 
 type TokenVec<'a> = SmallVec<[&'a str; 32]>;
 
@@ -47,6 +79,109 @@ fn nif_similarity_score<'a>(env: Env<'a>, a: String, b: String) -> NifResult<Ter
     Ok(score.encode(env))
 }
 
+/// Like `nif_similarity_score`, but takes a `ScoringConfig` (an Elixir map or
+/// keyword list) so callers can tune blend weights, window/chunk sizing, the
+/// partial‑similarity token cutoff, and the acceptance `cutoff` fed into the
+/// byte‑frequency prefilter. Bad weights or bounds are rejected during
+/// argument decoding, so malformed input surfaces as an `ArgumentError` on
+/// the Elixir side rather than silently falling back to defaults.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn nif_similarity_score_with<'a>(
+    env: Env<'a>,
+    a: String,
+    b: String,
+    config: ScoringConfig,
+) -> NifResult<Term<'a>> {
+    let score = panic::catch_unwind(|| {
+        similarity_score_prepared_with(&Prepared::new(&a), &Prepared::new(&b), &config)
+    })
+    .unwrap_or_else(|e| {
+        error!("panic inside similarity_score_prepared_with: {:?}", e);
+        0.0
+    });
+    Ok(score.encode(env))
+}
+
+/// Like `nif_similarity_score`, but looks the pair up in the BLAKE3-keyed
+/// LRU cache first and only runs `similarity_score` on a miss.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn nif_similarity_score_cached<'a>(env: Env<'a>, a: String, b: String) -> NifResult<Term<'a>> {
+    let score =
+        panic::catch_unwind(|| cache::similarity_score_cached(&a, &b)).unwrap_or_else(|e| {
+            error!("panic inside similarity_score_cached: {:?}", e);
+            0.0
+        });
+    Ok(score.encode(env))
+}
+
+/// Resize the similarity cache, evicting least-recently-used entries if
+/// shrinking.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn nif_cache_configure(capacity: usize) -> rustler::Atom {
+    if let Err(e) = panic::catch_unwind(|| cache::configure(capacity)) {
+        error!("panic inside cache::configure: {:?}", e);
+    }
+    rustler::types::atom::ok()
+}
+
+/// Drop every entry from the similarity cache.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn nif_cache_clear() -> rustler::Atom {
+    if let Err(e) = panic::catch_unwind(cache::clear) {
+        error!("panic inside cache::clear: {:?}", e);
+    }
+    rustler::types::atom::ok()
+}
+
+/// Rank `candidates` against `query` in parallel and return the top `k` as
+/// `(index, score)` pairs, sorted by descending score. The query is
+/// tokenised once and shared read‑only across the `rayon` worker pool.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn nif_similarity_top_k<'a>(
+    env: Env<'a>,
+    query: String,
+    candidates: Vec<String>,
+    k: usize,
+) -> NifResult<Term<'a>> {
+    let result =
+        panic::catch_unwind(|| top_k_similarity(&query, &candidates, k)).unwrap_or_else(|e| {
+            error!("panic inside top_k_similarity: {:?}", e);
+            Vec::new()
+        });
+    Ok(result.encode(env))
+}
+
+/// Build a token-blocked index over `corpus`, returned as an opaque
+/// `Resource` handle the Elixir side holds onto and passes back into
+/// `nif_index_query`.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn nif_index_build(corpus: Vec<String>) -> ResourceArc<IndexResource> {
+    let index = panic::catch_unwind(|| CorpusIndex::build(corpus)).unwrap_or_else(|e| {
+        error!("panic inside CorpusIndex::build: {:?}", e);
+        CorpusIndex::empty()
+    });
+    ResourceArc::new(IndexResource(index))
+}
+
+/// Query a previously built index: candidates are gathered as the union of
+/// postings for `query`'s tokens, those sharing fewer than
+/// `min_shared_tokens` distinct tokens are discarded, and survivors are
+/// scored with `similarity_score`.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn nif_index_query<'a>(
+    env: Env<'a>,
+    index: ResourceArc<IndexResource>,
+    query: String,
+    min_shared_tokens: usize,
+) -> NifResult<Term<'a>> {
+    let result =
+        panic::catch_unwind(|| index.0.query(&query, min_shared_tokens)).unwrap_or_else(|e| {
+            error!("panic inside CorpusIndex::query: {:?}", e);
+            Vec::new()
+        });
+    Ok(result.encode(env))
+}
+
 // Pre‑tokenised wrapper ───────────────────────────────────────────────
 
 #[derive(Debug)]
@@ -66,40 +201,141 @@ impl<'a> Prepared<'a> {
 //  Top‑level score ─────────────────────────────────────────────────────
 
 fn similarity_score(a: &str, b: &str) -> f64 {
+    similarity_score_prepared(&Prepared::new(a), &Prepared::new(b))
+}
+
+/// Core scoring pipeline over already‑tokenised inputs, so callers that reuse
+/// a `Prepared` across many comparisons (e.g. top‑k ranking) skip the
+/// re‑tokenisation `similarity_score` would otherwise redo per pair. Runs on
+/// `ScoringConfig::default()`; see `similarity_score_prepared_with` for the
+/// configurable entry point.
+fn similarity_score_prepared<'a>(prep_a: &Prepared<'a>, prep_b: &Prepared<'a>) -> f64 {
+    similarity_score_prepared_with(prep_a, prep_b, &ScoringConfig::default())
+}
+
+fn similarity_score_prepared_with<'a>(
+    prep_a: &Prepared<'a>,
+    prep_b: &Prepared<'a>,
+    config: &ScoringConfig,
+) -> f64 {
     // Treat the shorter tokenised string as the query.
-    let (prep_q, prep_t) = {
-        let a_p = Prepared::new(a);
-        let b_p = Prepared::new(b);
-        if a_p.tokens.len() <= b_p.tokens.len() {
-            (a_p, b_p)
-        } else {
-            (b_p, a_p)
-        }
+    let (prep_q, prep_t) = if prep_a.tokens.len() <= prep_b.tokens.len() {
+        (prep_a, prep_b)
+    } else {
+        (prep_b, prep_a)
     };
 
-    let partial = aggregated_partial_similarity(&prep_q, &prep_t);
-    let blended = blend_token_char(&prep_q, &prep_t);
+    let partial = aggregated_partial_similarity(prep_q, prep_t, config.cutoff, config);
+    let blended = blend_token_char(prep_q, prep_t, config.cutoff, config);
 
     let score = partial.max(blended);
     (score * ROUND_TO).round() / ROUND_TO
 }
 
+// Batch top‑k ranking ──────────────────────────────────────────────────
+
+/// A scored candidate kept in the bounded min‑heap used by `top_k_similarity`.
+/// `Ord` is reversed on `score` so the heap's peek/pop surfaces the *lowest*
+/// scoring entry, which is the one we want to evict once the heap overflows
+/// `k`.
+#[derive(Debug, Clone, Copy)]
+struct ScoredCandidate {
+    score: f64,
+    index: u32,
+}
+
+impl PartialEq for ScoredCandidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.score == other.score
+    }
+}
+
+impl Eq for ScoredCandidate {}
+
+impl PartialOrd for ScoredCandidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ScoredCandidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.score.total_cmp(&self.score)
+    }
+}
+
+fn push_bounded(heap: &mut BinaryHeap<ScoredCandidate>, candidate: ScoredCandidate, k: usize) {
+    heap.push(candidate);
+    if heap.len() > k {
+        heap.pop();
+    }
+}
+
+/// Score every candidate against `query` in parallel, keeping only the top
+/// `k` by score. The query is tokenised once via `Prepared` and shared
+/// read‑only across worker threads; each candidate is tokenised on its own
+/// thread as it's scored.
+fn top_k_similarity(query: &str, candidates: &[String], k: usize) -> Vec<(u32, f64)> {
+    if k == 0 || candidates.is_empty() {
+        return Vec::new();
+    }
+
+    let prep_query = Prepared::new(query);
+
+    let heap = candidates
+        .par_iter()
+        .enumerate()
+        .fold(
+            || BinaryHeap::with_capacity(k + 1),
+            |mut local: BinaryHeap<ScoredCandidate>, (index, candidate)| {
+                let score = similarity_score_prepared(&prep_query, &Prepared::new(candidate));
+                push_bounded(
+                    &mut local,
+                    ScoredCandidate {
+                        score,
+                        index: index as u32,
+                    },
+                    k,
+                );
+                local
+            },
+        )
+        .reduce(
+            || BinaryHeap::with_capacity(k + 1),
+            |mut acc, other| {
+                for candidate in other {
+                    push_bounded(&mut acc, candidate, k);
+                }
+                acc
+            },
+        );
+
+    let mut ranked: Vec<(u32, f64)> = heap.into_iter().map(|c| (c.index, c.score)).collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ranked
+}
+
 // Aggregated partial (chunking) ───────────────────────────────────────
 
-fn aggregated_partial_similarity(query: &Prepared<'_>, target: &Prepared<'_>) -> f64 {
+fn aggregated_partial_similarity(
+    query: &Prepared<'_>,
+    target: &Prepared<'_>,
+    threshold: f64,
+    config: &ScoringConfig,
+) -> f64 {
     if target.tokens.is_empty() {
         return 0.0;
     }
 
     let q_len = max(query.tokens.len(), 1);
     let chunk_len = (q_len * 3)
-        .clamp(CHUNK_MIN, CHUNK_MAX)
+        .clamp(config.chunk_min, config.chunk_max)
         .min(target.tokens.len());
 
     let mut total: f64 = 0.0;
     for chunk in target.tokens.chunks(chunk_len) {
         let span = span_from_tokens(target.raw, chunk);
-        total += partial_similarity(query.raw, span);
+        total += partial_similarity(query.raw, span, threshold, config);
         if total >= 1.0 {
             return 1.0;
         }
@@ -125,12 +361,12 @@ fn span_from_tokens<'a>(haystack: &'a str, toks: &[&'a str]) -> &'a str {
 
 // Sliding‑window partial ─────────────────────────────────────────────
 
-fn partial_similarity(query: &str, target: &str) -> f64 {
+fn partial_similarity(query: &str, target: &str, threshold: f64, config: &ScoringConfig) -> f64 {
     let q_tokens: TokenVec = query.split_whitespace().collect();
     let len_q = q_tokens.len();
 
-    if len_q <= 1 || len_q > 20 {
-        return blend_token_char_raw(query, target);
+    if len_q <= 1 || len_q > config.max_partial_tokens {
+        return blend_token_char_raw(query, target, threshold, config);
     }
 
     let t_tokens: TokenVec = target.split_whitespace().collect();
@@ -138,15 +374,20 @@ fn partial_similarity(query: &str, target: &str) -> f64 {
         return 0.0;
     }
 
-    let pad = ((len_q as f32) * WINDOW_PAD).ceil() as usize;
+    let pad = ((len_q as f32) * config.window_pad).ceil() as usize;
     let win_min = len_q.saturating_sub(pad).max(1);
     let win_max = min((len_q + pad).min(30), t_tokens.len());
 
+    // `best` is the best score seen so far; it (floored at `threshold`) is fed
+    // back in as each candidate's cutoff, so once a window can't beat what we
+    // already have, `blend_token_char_raw` can bail out of the DP. While
+    // `best` is below `threshold`, a window that bails out contributes its
+    // bound rather than its true score.
     let mut best: f64 = 0.0;
     for w in win_min..=win_max {
         for slice in t_tokens.windows(w) {
             let span = span_from_tokens(target, slice);
-            let cand = blend_token_char_raw(query, span);
+            let cand = blend_token_char_raw(query, span, best.max(threshold), config);
             if cand > best {
                 best = cand;
                 if best >= 1.0 {
@@ -161,15 +402,27 @@ fn partial_similarity(query: &str, target: &str) -> f64 {
 // Blend token‑bag & char metrics ─────────────────────────────────────
 
 #[inline]
-fn blend_token_char(a: &Prepared<'_>, b: &Prepared<'_>) -> f64 {
-    blend_token_char_raw(a.raw, b.raw)
+fn blend_token_char(
+    a: &Prepared<'_>,
+    b: &Prepared<'_>,
+    threshold: f64,
+    config: &ScoringConfig,
+) -> f64 {
+    blend_token_char_raw(a.raw, b.raw, threshold, config)
 }
 
-fn blend_token_char_raw(a: &str, b: &str) -> f64 {
+fn blend_token_char_raw(a: &str, b: &str, threshold: f64, config: &ScoringConfig) -> f64 {
     let token = token_jaccard_multiset(a, b);
-    let char_ = char_similarity(a, b);
+    // The token component contributes at most `config.token_weight` of the
+    // blend, so derive the char‑similarity cutoff that would make the blend
+    // just clear `threshold` and pass that down instead of `threshold` itself.
+    let char_threshold = match token {
+        Some(t) => (threshold - config.token_weight * t) / config.char_weight,
+        None => threshold,
+    };
+    let char_ = char_similarity(a, b, char_threshold, config);
     match token {
-        Some(t) => 0.7 * t + 0.3 * char_,
+        Some(t) => config.token_weight * t + config.char_weight * char_,
         None => char_,
     }
 }
@@ -214,7 +467,32 @@ fn token_jaccard_multiset(a: &str, b: &str) -> Option<f64> {
 
 // Character metric (Hamming → SIMD Levenshtein) ──────────────────────
 
-fn char_similarity(a: &str, b: &str) -> f64 {
+/// Byte‑frequency lower bound on edit distance: the length difference and
+/// half the total per‑byte count mismatch are both proven lower bounds on
+/// edit distance, and the larger of the two is a tighter one. O(n) versus
+/// the O(n·k) banded DP it lets us skip.
+fn char_distance_lower_bound(a: &str, b: &str) -> usize {
+    let mut counts_a = [0u32; 256];
+    let mut counts_b = [0u32; 256];
+    for &byte in a.as_bytes() {
+        counts_a[byte as usize] += 1;
+    }
+    for &byte in b.as_bytes() {
+        counts_b[byte as usize] += 1;
+    }
+
+    let freq_diff: u32 = counts_a
+        .iter()
+        .zip(counts_b.iter())
+        .map(|(&ca, &cb)| ca.abs_diff(cb))
+        .sum();
+    let freq_bound = freq_diff.div_ceil(2) as usize;
+    let len_bound = (a.len() as isize - b.len() as isize).unsigned_abs();
+
+    max(len_bound, freq_bound)
+}
+
+fn char_similarity(a: &str, b: &str, threshold: f64, config: &ScoringConfig) -> f64 {
     let (a_len, b_len) = (a.len(), b.len());
 
     match (a_len, b_len) {
@@ -223,21 +501,131 @@ fn char_similarity(a: &str, b: &str) -> f64 {
         _ => {}
     }
 
-    if (a_len as isize - b_len as isize).abs() as usize <= HAMMING_WINDOW {
+    if (a_len as isize - b_len as isize).unsigned_abs() <= HAMMING_WINDOW {
         let len = min(a_len, b_len);
         let mismatches = hamming(&a.as_bytes()[..len], &b.as_bytes()[..len]);
         return 1.0 - mismatches as f64 * (1.0 / len as f64);
     }
 
-    let k_band = if a_len.max(b_len) <= SHORT_STRING {
-        SHORT_STRING as u32
+    let max_len = a_len.max(b_len);
+    let bound = 1.0 - char_distance_lower_bound(a, b) as f64 / max_len as f64;
+    if bound < threshold {
+        // Can't possibly clear the caller's bar; skip the DP entirely.
+        return bound.max(0.0);
+    }
+
+    let k_band = if max_len <= config.short_string {
+        config.short_string as u32
     } else {
-        a_len.max(b_len) as u32
+        max_len as u32
     };
 
     levenshtein_simd_k(a.as_bytes(), b.as_bytes(), k_band)
-        .map(|dist| 1.0 - dist as f64 / a_len.max(b_len) as f64)
+        .map(|dist| 1.0 - dist as f64 / max_len as f64)
         .unwrap_or(0.0)
 }
 
 rustler::init!("Elixir.Fuzler");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Regression tests for the threshold threaded through `char_similarity` /
+    // `blend_token_char_raw` / `partial_similarity`: the prefilter must not
+    // change a score that clears the threshold, only how cheaply it's
+    // computed; a score below the threshold comes back as the bound instead.
+
+    #[test]
+    fn char_similarity_matches_the_unfiltered_result_when_the_bound_clears_the_bar() {
+        // Length differs by more than HAMMING_WINDOW, so this exercises the
+        // byte-frequency lower-bound branch rather than the Hamming fast path.
+        let a = "abcdefghijklmnopqrst";
+        let b = "abcdefghijklmnopqrstuvwxy";
+        let config = ScoringConfig::default();
+
+        let unfiltered = char_similarity(a, b, 0.0, &config);
+        let filtered = char_similarity(a, b, 0.5, &config);
+
+        assert_eq!(unfiltered, filtered);
+    }
+
+    #[test]
+    fn char_similarity_short_circuits_once_the_bound_falls_below_the_threshold() {
+        let a = "aaaaaaaaaaaaaaaaaaaa";
+        let b = "zzzzz";
+        let config = ScoringConfig::default();
+        let max_len = a.len().max(b.len()) as f64;
+        let bound = 1.0 - char_distance_lower_bound(a, b) as f64 / max_len;
+
+        // A cutoff just above the bound must trigger the short-circuit and
+        // hand back the bound itself rather than running the banded DP.
+        let filtered = char_similarity(a, b, bound + 0.01, &config);
+        assert_eq!(filtered, bound);
+
+        // The real (unfiltered) score is strictly lower than the bound, since
+        // the bound is only ever a provable upper bound on similarity.
+        let unfiltered = char_similarity(a, b, 0.0, &config);
+        assert!(unfiltered < bound);
+    }
+
+    #[test]
+    fn partial_similarity_is_unaffected_by_a_threshold_below_the_true_best() {
+        let query = "apple banana cherry";
+        let target = "apple bananas cherry durian fig";
+        let config = ScoringConfig::default();
+
+        let unfiltered = partial_similarity(query, target, 0.0, &config);
+        let filtered = partial_similarity(query, target, 0.1, &config);
+
+        assert_eq!(unfiltered, filtered);
+    }
+
+    #[test]
+    fn similarity_score_of_identical_strings_is_one() {
+        assert_eq!(similarity_score("hello world", "hello world"), 1.0);
+    }
+
+    // `top_k_similarity` / `ScoredCandidate` ─────────────────────────────
+
+    #[test]
+    fn scored_candidate_min_heap_evicts_the_lowest_score_first() {
+        // `Ord` is reversed on `score`, so the heap's peek/pop surfaces the
+        // lowest-scoring candidate, which is what `push_bounded` evicts once
+        // the heap grows past `k`.
+        let mut heap = BinaryHeap::new();
+        for (index, score) in [(0u32, 0.9), (1, 0.1), (2, 0.5)] {
+            push_bounded(&mut heap, ScoredCandidate { score, index }, 2);
+        }
+
+        assert_eq!(heap.len(), 2);
+        let remaining: Vec<u32> = heap.into_iter().map(|c| c.index).collect();
+        assert!(remaining.contains(&0));
+        assert!(remaining.contains(&2));
+        assert!(!remaining.contains(&1));
+    }
+
+    #[test]
+    fn top_k_similarity_ranks_candidates_by_descending_score() {
+        let candidates = vec![
+            "completely unrelated text".to_string(),
+            "hello world".to_string(),
+            "hello there world".to_string(),
+        ];
+
+        let ranked = top_k_similarity("hello world", &candidates, 2);
+
+        assert_eq!(ranked.len(), 2);
+        assert_eq!(ranked[0].0, 1); // exact match ranks first
+        assert!(ranked[0].1 >= ranked[1].1);
+    }
+
+    #[test]
+    fn top_k_similarity_handles_k_zero_and_empty_candidates() {
+        assert_eq!(top_k_similarity("hello", &[], 5), Vec::new());
+        assert_eq!(
+            top_k_similarity("hello", &["world".to_string()], 0),
+            Vec::new()
+        );
+    }
+}